@@ -0,0 +1,42 @@
+//! The command-line surface for `load`. Replaces the old ad-hoc
+//! `env::args().nth(1)` handling: precision and the currency's backing
+//! integer (see [`crate::currency::Backing`]) used to be compile-time
+//! constants, and are now flags the caller picks per run.
+
+use clap::Parser;
+
+/// A fast CSV/binary transaction ledger.
+#[derive(Debug, Parser)]
+pub struct Cli {
+    /// Decimal digits of precision for currency amounts.
+    #[arg(long, default_value_t = 4)]
+    pub precision: u32,
+
+    /// Back currency amounts with `i128` instead of the default `i64`, for
+    /// inputs whose magnitudes could otherwise overflow.
+    #[arg(long)]
+    pub wide: bool,
+
+    /// Number of worker threads to shard large inputs across. Defaults to
+    /// the number of available cores; ignored for inputs below the
+    /// parallel-processing size threshold.
+    #[arg(long)]
+    pub workers: Option<usize>,
+
+    /// Path to the transactions file. Its format (CSV, or the binary codec
+    /// for a `.bin` file) is picked from the extension. Mutually exclusive
+    /// with `--stdin`.
+    #[arg(long, conflicts_with = "stdin", required_unless_present = "stdin")]
+    pub input: Option<String>,
+
+    /// Read CSV transactions from standard input instead of a file.
+    #[arg(long)]
+    pub stdin: bool,
+
+    /// Spill the transaction log to disk instead of keeping it in memory
+    /// (see `crate::store::DiskStore`), for inputs too large to hold every
+    /// transaction in RAM. When set, `--workers` is ignored: sharding only
+    /// applies to the in-memory store.
+    #[arg(long)]
+    pub disk_store: bool,
+}
@@ -3,105 +3,209 @@
 //! currency (multiplied by 10^N) accurately.
 //!
 //! There is no way to know what the maximum value may be. The currency is
-//! unknown as well, so we can't assume anything about its range. It is given
-//! u64 as its representation, whose maximum value is exactly
+//! unknown as well, so we can't assume anything about its range. By default
+//! `PreciseCurrency` is backed by `i64`, whose maximum value is exactly
 //! Â±9,223,372,036,854,775,807. If four of these are decimal values, it is safe
 //! to assume that having roughly four times more digits for the rest (15
-//! digits) is enough. Making the switch to u128 would allow a range large
-//! enough that overflows are out of the question, but that would greatly impact
-//! the performance, since its operations are much slower.
+//! digits) is enough for most inputs. For the rare input whose magnitudes
+//! could overflow that, the backing integer is itself a generic parameter
+//! (see [`Backing`]): switching it to `i128` allows a range large enough
+//! that overflows are out of the question, at the cost of slower arithmetic.
+//! The CLI exposes this tradeoff directly as `--wide`, instead of it being a
+//! compile-time constant baked into one binary.
 //!
 //! Additionally, we will need to make sure no overflows occur. This could be
 //! done with the `Saturating` wrapper [1], but it's not stable yet,
-//! unfortunately. We will stick to using the `saturating` methods in `u64` to
-//! avoid using a new library.
+//! unfortunately. We will stick to using the `saturating` methods in the
+//! backing integer to avoid using a new library.
 //!
 //! [1] <https://doc.rust-lang.org/std/num/struct.Saturating.html>
 
 use std::{
     fmt,
+    marker::PhantomData,
     ops::{Add, AddAssign, Sub, SubAssign},
 };
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
-/// A number precise up to `N` digits.
+/// The integer type backing a [`PreciseCurrency`]'s fixed-point value.
+/// Implemented only for `i64` and `i128`, so that the overflow-vs-speed
+/// tradeoff described in the module docs above is a value the caller picks
+/// at runtime (see the `--wide` CLI flag) rather than a type baked in at
+/// compile time for the whole binary.
+pub trait Backing:
+    Copy
+    + Clone
+    + fmt::Debug
+    + fmt::Display
+    + Default
+    + PartialEq
+    + Eq
+    + PartialOrd
+    + Ord
+    + Send
+    + Sync
+    + Serialize
+    + DeserializeOwned
+    + 'static
+{
+    /// Builds a raw fixed-point value directly, with no precision applied.
+    fn from_i64(v: i64) -> Self;
+    /// Builds a raw fixed-point value from an already-scaled float, i.e.
+    /// `v` is expected to already be multiplied by `10^N`.
+    fn from_scaled_f64(v: f64) -> Self;
+    fn saturating_add(self, rhs: Self) -> Self;
+    fn saturating_sub(self, rhs: Self) -> Self;
+    fn is_negative(self) -> bool;
+    fn abs(self) -> Self;
+    /// `10^n` in this backing type, used to split a raw value into its
+    /// integer and decimal parts.
+    fn pow10(n: u32) -> Self;
+    /// `(self / rhs, self % rhs)`, used to split a raw value using
+    /// [`pow10`](Self::pow10).
+    fn div_rem(self, rhs: Self) -> (Self, Self);
+}
+
+macro_rules! impl_backing {
+    ($ty:ty) => {
+        impl Backing for $ty {
+            fn from_i64(v: i64) -> Self {
+                v as $ty
+            }
+
+            fn from_scaled_f64(v: f64) -> Self {
+                v as $ty
+            }
+
+            fn saturating_add(self, rhs: Self) -> Self {
+                <$ty>::saturating_add(self, rhs)
+            }
+
+            fn saturating_sub(self, rhs: Self) -> Self {
+                <$ty>::saturating_sub(self, rhs)
+            }
+
+            fn is_negative(self) -> bool {
+                self < 0
+            }
+
+            fn abs(self) -> Self {
+                <$ty>::abs(self)
+            }
+
+            fn pow10(n: u32) -> Self {
+                (10 as $ty).pow(n)
+            }
+
+            fn div_rem(self, rhs: Self) -> (Self, Self) {
+                (self / rhs, self % rhs)
+            }
+        }
+    };
+}
+
+impl_backing!(i64);
+impl_backing!(i128);
+
+/// A number precise up to `N` digits, backed by `B` (see [`Backing`]).
 #[repr(transparent)]
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
-pub struct PreciseCurrency<const N: u32>(i64);
+pub struct PreciseCurrency<B: Backing, const N: u32>(B);
 
 /// Custom serialization that takes floating points
-impl<const N: u32> Serialize for PreciseCurrency<N> {
+impl<B: Backing, const N: u32> Serialize for PreciseCurrency<B, N> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let symbol = if self.0 < 0 { "-" } else { "" };
+        let symbol = if self.0.is_negative() { "-" } else { "" };
         let abs = self.0.abs();
-        let precision = 10_i64.pow(N);
-        let integer = abs / precision;
-        let decimals = abs % precision;
-        let digits = format!("{symbol}{integer}.{decimals:0>4}");
+        let (integer, decimals) = abs.div_rem(B::pow10(N));
+        let digits = format!("{symbol}{integer}.{decimals:0>width$}", width = N as usize);
         serializer.serialize_str(&digits)
     }
 }
 
 /// Custom deserialization that outputs floating points
-impl<'de, const N: u32> Deserialize<'de> for PreciseCurrency<N> {
+impl<'de, B: Backing, const N: u32> Deserialize<'de> for PreciseCurrency<B, N> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
         /// Vistor to help deserialize currency
-        pub struct CurrencyVisitor<const N: u32>;
-        impl<'de, const N: u32> serde::de::Visitor<'de> for CurrencyVisitor<N> {
-            type Value = PreciseCurrency<N>;
+        pub struct CurrencyVisitor<B, const N: u32>(PhantomData<B>);
+        impl<'de, B: Backing, const N: u32> serde::de::Visitor<'de> for CurrencyVisitor<B, N> {
+            type Value = PreciseCurrency<B, N>;
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                write!(formatter, "a currency with four digits of precision")
+                write!(formatter, "a currency with {N} digits of precision")
             }
 
             fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
             where
                 E: serde::de::Error,
             {
-                Ok(PreciseCurrency((v * 10_u64.pow(N) as f64) as i64))
+                Ok(PreciseCurrency(B::from_scaled_f64(
+                    v * 10_f64.powi(N as i32),
+                )))
             }
         }
 
-        deserializer.deserialize_f64(CurrencyVisitor::<N>)
+        deserializer.deserialize_f64(CurrencyVisitor::<B, N>(PhantomData))
     }
 }
 
 /// Converting from the original type
-impl<const N: u32> From<i64> for PreciseCurrency<N> {
+impl<B: Backing, const N: u32> From<i64> for PreciseCurrency<B, N> {
     fn from(v: i64) -> Self {
-        Self(v)
+        Self(B::from_i64(v))
+    }
+}
+
+impl<B: Backing, const N: u32> PreciseCurrency<B, N> {
+    /// The raw fixed-point integer this currency wraps, with no precision
+    /// applied — i.e. the same representation [`From<i64>`] builds from.
+    /// Used by the binary codec to avoid the lossy `f64` round-trip in
+    /// [`Deserialize`].
+    pub fn raw(&self) -> B {
+        self.0
+    }
+
+    /// Builds a currency value directly from a raw fixed-point integer
+    /// (the inverse of [`raw`](Self::raw)), with no scaling applied. Unlike
+    /// [`From<i64>`], it takes the backing integer `B` itself, so the full
+    /// `i128` range is available. Used by [`crate::store::DiskStore`] to spill
+    /// transactions to disk without going through the lossy `f64`
+    /// round-trip [`Deserialize`] goes through.
+    pub(crate) fn from_raw(raw: B) -> Self {
+        Self(raw)
     }
 }
 
 /// Custom `Saturating` wrapper
-impl<const N: u32> Add for PreciseCurrency<N> {
+impl<B: Backing, const N: u32> Add for PreciseCurrency<B, N> {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
         Self(self.0.saturating_add(rhs.0))
     }
 }
-impl<const N: u32> AddAssign for PreciseCurrency<N> {
+impl<B: Backing, const N: u32> AddAssign for PreciseCurrency<B, N> {
     fn add_assign(&mut self, rhs: Self) {
         self.0 = self.0.saturating_add(rhs.0);
     }
 }
 
 /// Custom `Saturating` wrapper
-impl<const N: u32> Sub for PreciseCurrency<N> {
+impl<B: Backing, const N: u32> Sub for PreciseCurrency<B, N> {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
         Self(self.0.saturating_sub(rhs.0))
     }
 }
-impl<const N: u32> SubAssign for PreciseCurrency<N> {
+impl<B: Backing, const N: u32> SubAssign for PreciseCurrency<B, N> {
     fn sub_assign(&mut self, rhs: Self) {
         self.0 = self.0.saturating_sub(rhs.0);
     }
@@ -109,11 +213,14 @@ impl<const N: u32> SubAssign for PreciseCurrency<N> {
 
 #[cfg(test)]
 mod tests {
-    use super::PreciseCurrency;
+    use super::{Backing, PreciseCurrency};
 
     use anyhow::Result;
 
-    fn try_serialize(test_cur: PreciseCurrency<4>, expected: &str) -> Result<()> {
+    fn try_serialize<B: Backing, const N: u32>(
+        test_cur: PreciseCurrency<B, N>,
+        expected: &str,
+    ) -> Result<()> {
         let mut writer = csv::Writer::from_writer(vec![]);
         writer.serialize(test_cur)?;
 
@@ -123,53 +230,79 @@ mod tests {
         Ok(())
     }
 
-    fn try_deserialize(test_str: &str, expected: PreciseCurrency<4>) -> Result<()> {
+    fn try_deserialize<B: Backing, const N: u32>(
+        test_str: &str,
+        expected: PreciseCurrency<B, N>,
+    ) -> Result<()> {
         let test_str = format!("x\n{test_str}\n");
         let mut reader = csv::Reader::from_reader(test_str.as_bytes());
-        let data = reader.deserialize::<PreciseCurrency<4>>().next().unwrap()?;
+        let data = reader
+            .deserialize::<PreciseCurrency<B, N>>()
+            .next()
+            .unwrap()?;
 
         assert_eq!(data, expected, "deserialization");
 
         Ok(())
     }
 
-    fn try_both(test_cur: PreciseCurrency<4>, test_str: &str) -> Result<()> {
+    fn try_both<B: Backing, const N: u32>(
+        test_cur: PreciseCurrency<B, N>,
+        test_str: &str,
+    ) -> Result<()> {
         try_serialize(test_cur, test_str)?;
         try_deserialize(test_str, test_cur)
     }
 
     #[test]
     fn test_big() -> Result<()> {
-        try_both(PreciseCurrency(9876543210_i64), "987654.3210")?;
-        try_both(PreciseCurrency(-9876543210_i64), "-987654.3210")
+        try_both(PreciseCurrency::<i64, 4>::from(9876543210_i64), "987654.3210")?;
+        try_both(PreciseCurrency::<i64, 4>::from(-9876543210_i64), "-987654.3210")
     }
     #[test]
     fn test_full() -> Result<()> {
-        try_both(PreciseCurrency(123444_i64), "12.3444")?;
-        try_both(PreciseCurrency(-123444_i64), "-12.3444")
+        try_both(PreciseCurrency::<i64, 4>::from(123444_i64), "12.3444")?;
+        try_both(PreciseCurrency::<i64, 4>::from(-123444_i64), "-12.3444")
     }
     #[test]
     fn test_integer() -> Result<()> {
-        try_both(PreciseCurrency(140000_i64), "14.0000")?;
-        try_both(PreciseCurrency(-140000_i64), "-14.0000")
+        try_both(PreciseCurrency::<i64, 4>::from(140000_i64), "14.0000")?;
+        try_both(PreciseCurrency::<i64, 4>::from(-140000_i64), "-14.0000")
     }
     #[test]
     fn test_decimals() -> Result<()> {
-        try_both(PreciseCurrency(1234_i64), "0.1234")?;
-        try_both(PreciseCurrency(-1234_i64), "-0.1234")
+        try_both(PreciseCurrency::<i64, 4>::from(1234_i64), "0.1234")?;
+        try_both(PreciseCurrency::<i64, 4>::from(-1234_i64), "-0.1234")
     }
     #[test]
     fn test_partial1() -> Result<()> {
-        try_both(PreciseCurrency(123_i64), "0.0123")?;
-        try_both(PreciseCurrency(-123_i64), "-0.0123")
+        try_both(PreciseCurrency::<i64, 4>::from(123_i64), "0.0123")?;
+        try_both(PreciseCurrency::<i64, 4>::from(-123_i64), "-0.0123")
     }
     #[test]
     fn test_partial2() -> Result<()> {
-        try_both(PreciseCurrency(10_i64), "0.0010")?;
-        try_both(PreciseCurrency(-10_i64), "-0.0010")
+        try_both(PreciseCurrency::<i64, 4>::from(10_i64), "0.0010")?;
+        try_both(PreciseCurrency::<i64, 4>::from(-10_i64), "-0.0010")
     }
     #[test]
     fn test_zero() -> Result<()> {
-        try_both(PreciseCurrency(0_i64), "0.0000")
+        try_both(PreciseCurrency::<i64, 4>::from(0_i64), "0.0000")
+    }
+    #[test]
+    fn test_wide_backing() -> Result<()> {
+        // `i128` needs to round-trip exactly like `i64`, just with room for
+        // much bigger magnitudes.
+        try_both(
+            PreciseCurrency::<i128, 4>::from(123444_i64),
+            "12.3444",
+        )?;
+        try_both(
+            PreciseCurrency::<i128, 4>::from(-123444_i64),
+            "-12.3444",
+        )
+    }
+    #[test]
+    fn test_other_precision() -> Result<()> {
+        try_both(PreciseCurrency::<i64, 2>::from(1234_i64), "12.34")
     }
 }
@@ -1,150 +1,178 @@
+pub mod binary;
+pub mod cli;
 pub mod currency;
 pub mod model;
+pub mod store;
 
-use model::{AllBalances, Balance, DisputeState, Transaction, TransactionType};
-
-use std::collections::HashMap;
-use std::env;
-
-use anyhow::{anyhow, Result};
-
-impl Balance {
-    /// Deposits are the only valid first transaction. But even if the first one
-    /// is an invalid withdrawal, it should be saved internally to avoid
-    /// duplicate transaction IDs. The rest of the types should still be
-    /// ignored, in which case `None` is returned.
-    fn from_tx(tx: Transaction) -> Option<Self> {
-        match tx {
-            Transaction {
-                _type: TransactionType::Deposit,
-                amount: Some(amount),
-                ..
-            } => Some(Balance {
-                client: tx.client,
-                available: amount,
-                total: amount,
-                transactions: {
-                    let mut map = HashMap::with_capacity(1);
-                    map.insert(tx.tx, tx);
-                    map
-                },
-                ..Balance::default()
-            }),
-            Transaction {
-                _type: TransactionType::Withdrawal,
-                ..
-            } => Some(Balance {
-                client: tx.client,
-                transactions: {
-                    let mut map = HashMap::with_capacity(1);
-                    map.insert(tx.tx, tx);
-                    map
-                },
-                ..Balance::default()
-            }),
-            _ => None,
-        }
+use cli::Cli;
+use currency::Backing;
+use model::{AllBalances, DisputedAmount, Transaction};
+use store::{DiskStore, MemStore, Store};
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+use serde::Serialize;
+
+/// Below this many bytes, the fixed cost of spinning up worker threads and
+/// shipping transactions across channels outweighs any parallel speedup, so
+/// [`load`] sticks to the single-threaded [`process`].
+const PARALLEL_THRESHOLD_BYTES: u64 = 1 << 20;
+
+/// How many transactions a shard's channel buffers before the reader blocks
+/// on a slow worker. Bounded so a fast reader racing far ahead of the
+/// workers can't balloon memory on a huge input.
+const SHARD_CHANNEL_BOUND: usize = 1024;
+
+/// Why the ledger declined to apply a transaction, instead of silently
+/// dropping it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerError {
+    /// A withdrawal asked for more than the account's `available` funds.
+    InsufficientFunds,
+    /// The account is frozen after a chargeback; no further operations are
+    /// accepted.
+    AccountLocked,
+    /// A dispute/resolve/chargeback referenced a transaction ID nothing
+    /// recorded.
+    UnknownTransaction,
+    /// A deposit/withdrawal reused a transaction ID that's already on file.
+    DuplicateTransaction,
+    /// A dispute targeted a transaction that's already under dispute.
+    AlreadyDisputed,
+    /// A resolve/chargeback targeted a transaction that isn't under dispute.
+    NotDisputed,
+    /// A dispute/resolve/chargeback referenced a transaction ID that exists,
+    /// but belongs to a different client than the one who sent it.
+    ClientMismatch,
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            LedgerError::InsufficientFunds => "insufficient available funds",
+            LedgerError::AccountLocked => "account is locked",
+            LedgerError::UnknownTransaction => "unknown transaction",
+            LedgerError::DuplicateTransaction => "duplicate transaction id",
+            LedgerError::AlreadyDisputed => "transaction is already disputed",
+            LedgerError::NotDisputed => "transaction is not under dispute",
+            LedgerError::ClientMismatch => "transaction belongs to a different client",
+        };
+        write!(f, "{msg}")
     }
+}
 
-    /// Applies a new transaction of any kind to the balance.
-    fn apply_tx(&mut self, tx: Transaction) {
-        // Frozen accounts should be ignored
-        if self.locked {
-            return;
-        }
+impl std::error::Error for LedgerError {}
 
-        // The actual transaction engine, implemented as described in the
-        // `README.md`.
-        match tx._type {
-            TransactionType::Deposit => {
-                // If the transaction already exists, do nothing
-                self.transactions.entry(tx.tx).or_insert_with(|| {
-                    let amount = tx.amount.unwrap();
-                    self.available += amount;
-                    self.total += amount;
-                    tx
-                });
-            }
-            TransactionType::Withdrawal => {
-                let amount = tx.amount.unwrap();
-                // Operation is cancelled if there aren't enough available funds
-                if self.available - amount < 0.into() {
-                    return;
-                }
+/// A transaction the ledger declined to apply, kept around so operators can
+/// audit why funds didn't move instead of the failure silently vanishing.
+#[derive(Debug, Serialize)]
+pub struct Rejection {
+    pub client: u16,
+    pub tx: u32,
+    pub error: LedgerError,
+}
+
+/// Applies a single transaction against `store`, recording it and updating
+/// the account it belongs to. This is the actual transaction engine,
+/// implemented as described in the `README.md`. Each [`Transaction`] variant
+/// structurally carries the fields it needs, so there's no `amount` to
+/// unwrap here. The outer `Result` is for hard failures (e.g. the store's
+/// backing disk I/O); the inner one reports why a transaction was rejected.
+/// Generic over the currency's [`Backing`] integer and its decimal
+/// precision `N`, both chosen by the caller.
+fn apply_tx<S, B, const N: u32>(store: &mut S, tx: Transaction<B, N>) -> Result<Result<(), LedgerError>>
+where
+    B: Backing,
+    S: Store<B, N>,
+{
+    let client = tx.client();
+    let id = tx.tx();
+
+    // Frozen accounts should be ignored
+    if store.get_account(client).locked {
+        return Ok(Err(LedgerError::AccountLocked));
+    }
 
-                // If the transaction already exists, do nothing
-                self.transactions.entry(tx.tx).or_insert_with(|| {
-                    self.available -= amount;
-                    self.total -= amount;
-                    tx
-                });
+    Ok(match tx {
+        Transaction::Deposit { amount, .. } => {
+            if !store.record_tx(tx)? {
+                Err(LedgerError::DuplicateTransaction)
+            } else {
+                let account = store.get_account(client);
+                account.available += amount;
+                account.total += amount;
+                Ok(())
             }
-            TransactionType::Dispute => {
-                // If the transaction doesn't exist, do nothing.
-                if let Some(tx) = self.transactions.get(&tx.tx) {
-                    // If its entry already exists, i.e., it's already disputed
-                    // or resolved, it can't be disputed again.
-                    self.disputes.entry(tx.tx).or_insert_with(|| {
-                        let amount = tx.amount.unwrap();
-                        // No need to do anything for withdrawals
-                        if tx._type == TransactionType::Deposit {
-                            self.available -= amount;
-                            self.held += amount;
-                        }
-                        DisputeState::Waiting
-                    });
+        }
+        Transaction::Withdrawal { amount, .. } => {
+            // Operation is rejected if there aren't enough available funds
+            if store.get_account(client).available - amount < 0.into() {
+                Err(LedgerError::InsufficientFunds)
+            } else if !store.record_tx(tx)? {
+                Err(LedgerError::DuplicateTransaction)
+            } else {
+                let account = store.get_account(client);
+                account.available -= amount;
+                account.total -= amount;
+                Ok(())
+            }
+        }
+        Transaction::Dispute { .. } => match store.get_tx(id)? {
+            None => Err(LedgerError::UnknownTransaction),
+            Some(disputed) if disputed.client() != client => Err(LedgerError::ClientMismatch),
+            Some(disputed) => {
+                let amount = DisputedAmount::from(disputed);
+                let state = store.get_tx_state(id);
+                let account = store.get_account(client);
+                match state.dispute(account, amount) {
+                    Some(new_state) => {
+                        store.set_tx_state(id, new_state);
+                        Ok(())
+                    }
+                    None => Err(LedgerError::AlreadyDisputed),
                 }
             }
-            TransactionType::Resolve => {
-                // If the transaction doesn't exist, do nothing.
-                if let Some(tx) = self.transactions.get(&tx.tx) {
-                    // If no entry exists, i.e., it's undisputed, or if it's
-                    // already resolved, nothing should happen.
-                    match self.disputes.get_mut(&tx.tx) {
-                        None | Some(DisputeState::Resolved) => {}
-                        Some(ds @ DisputeState::Waiting) => {
-                            let amount = tx.amount.unwrap();
-                            // No need to do anything for withdrawals
-                            if tx._type == TransactionType::Deposit {
-                                self.available += amount;
-                                self.held -= amount;
-                            }
-                            *ds = DisputeState::Resolved;
-                        }
+        },
+        Transaction::Resolve { .. } => match store.get_tx(id)? {
+            None => Err(LedgerError::UnknownTransaction),
+            Some(disputed) if disputed.client() != client => Err(LedgerError::ClientMismatch),
+            Some(disputed) => {
+                let amount = DisputedAmount::from(disputed);
+                let state = store.get_tx_state(id);
+                let account = store.get_account(client);
+                match state.resolve(account, amount) {
+                    Some(new_state) => {
+                        store.set_tx_state(id, new_state);
+                        Ok(())
                     }
+                    None => Err(LedgerError::NotDisputed),
                 }
             }
-            TransactionType::Chargeback => {
-                // If the transaction doesn't exist, do nothing.
-                if let Some(tx) = self.transactions.get(&tx.tx) {
-                    // If no entry exists, i.e., it's undisputed, or if it's
-                    // already resolved, nothing should happen.
-                    match self.disputes.get_mut(&tx.tx) {
-                        None | Some(DisputeState::Resolved) => {}
-                        Some(ds @ DisputeState::Waiting) => {
-                            let amount = tx.amount.unwrap();
-                            match tx._type {
-                                TransactionType::Deposit => {
-                                    self.held -= amount;
-                                    self.total -= amount;
-                                }
-                                TransactionType::Withdrawal => {
-                                    self.available += amount;
-                                    self.total += amount;
-                                }
-                                _ => {}
-                            }
-                            self.locked = true;
-                            // Doesn't really matter here anyway, since its
-                            // account is now frozen and no other operations
-                            // will be performed.
-                            *ds = DisputeState::Resolved;
-                        }
+        },
+        Transaction::Chargeback { .. } => match store.get_tx(id)? {
+            None => Err(LedgerError::UnknownTransaction),
+            Some(disputed) if disputed.client() != client => Err(LedgerError::ClientMismatch),
+            Some(disputed) => {
+                let amount = DisputedAmount::from(disputed);
+                let state = store.get_tx_state(id);
+                let account = store.get_account(client);
+                match state.chargeback(account, amount) {
+                    Some(new_state) => {
+                        store.set_tx_state(id, new_state);
+                        Ok(())
                     }
+                    None => Err(LedgerError::NotDisputed),
                 }
             }
-        }
-    }
+        },
+    })
 }
 
 /// It's possible that the csv has spacing between fields, so we must enable the
@@ -155,50 +183,434 @@ pub fn init_reader(file: &str) -> csv::Result<csv::Reader<std::fs::File>> {
         .from_path(file)
 }
 
-/// Given an input file, return the final balances.
-pub fn process(file: &str) -> Result<AllBalances> {
-    let mut balances = AllBalances::new();
-    let mut reader = init_reader(file)?;
+/// Drives every transaction out of `records` through `store`, the shared
+/// tail of [`process`] and [`process_stdin`] once each has its own CSV
+/// reader set up.
+fn process_records<S, B, const N: u32>(
+    records: impl Iterator<Item = csv::Result<Transaction<B, N>>>,
+    mut store: S,
+) -> Result<(AllBalances<B, N>, Vec<Rejection>)>
+where
+    B: Backing,
+    S: Store<B, N>,
+{
+    let mut rejections = Vec::new();
 
-    for result in reader.deserialize::<Transaction>() {
+    for result in records {
         // Error resilience: the program tries to continue after finding an
         // erroneous entry.
         match result {
             Ok(entry) => {
-                let client = entry.client;
-                match balances.get_mut(&client) {
-                    // Uninitialized client
-                    None => {
-                        if let Some(balance) = Balance::from_tx(entry) {
-                            balances.insert(client, balance);
-                        }
+                let client = entry.client();
+                let tx = entry.tx();
+                if let Err(error) = apply_tx(&mut store, entry)? {
+                    rejections.push(Rejection { client, tx, error });
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to read CSV entry: {e}");
+            }
+        }
+    }
+
+    Ok((store.into_balances(), rejections))
+}
+
+/// Given an input file, apply every transaction in it to `store` and return
+/// the final balances alongside a report of every transaction the ledger
+/// rejected. Generic over the [`Store`] backing the engine, so callers can
+/// pick an in-memory or disk-backed implementation depending on how large
+/// the input is, and over the currency's [`Backing`] integer and decimal
+/// precision `N`.
+pub fn process<S, B, const N: u32>(file: &str, store: S) -> Result<(AllBalances<B, N>, Vec<Rejection>)>
+where
+    B: Backing,
+    S: Store<B, N>,
+{
+    let mut reader = init_reader(file)?;
+    process_records(reader.deserialize::<Transaction<B, N>>(), store)
+}
+
+/// Like [`process`], but reads CSV transactions from standard input instead
+/// of a file, for the `--stdin` CLI flag.
+pub fn process_stdin<S, B, const N: u32>(store: S) -> Result<(AllBalances<B, N>, Vec<Rejection>)>
+where
+    B: Backing,
+    S: Store<B, N>,
+{
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(io::stdin());
+    process_records(reader.deserialize::<Transaction<B, N>>(), store)
+}
+
+/// Like [`process`], but shards the transaction log across `workers`
+/// threads instead of applying it on the current one. Every balance
+/// mutation is scoped to a single `client`, so the work is embarrassingly
+/// parallel: each deserialized transaction is hashed by `client` into one of
+/// `workers` bounded channels, and each channel is drained in order by a
+/// thread that owns a disjoint slice of [`AllBalances`] via its own `S`.
+/// Because a channel preserves the order transactions were sent in, and
+/// every transaction for a given client always goes to the same channel,
+/// per-client ordering — the only ordering the dispute/resolve/chargeback
+/// logic depends on — is preserved within a shard. There's no ordering
+/// across shards, and no client's balance is ever touched by more than one
+/// of them. Each shard records transaction IDs in its own `S`, so a
+/// deposit/withdrawal reusing an ID already recorded in another shard is
+/// applied rather than rejected as a duplicate the way [`process`] rejects
+/// it, and a dispute/resolve/chargeback naming a transaction recorded in
+/// another shard is rejected as unknown. The per-shard balances and
+/// rejections are merged once every worker has drained its channel.
+pub fn process_parallel<S, B, const N: u32>(
+    file: &str,
+    workers: usize,
+) -> Result<(AllBalances<B, N>, Vec<Rejection>)>
+where
+    B: Backing,
+    S: Store<B, N> + Default + Send + 'static,
+{
+    let workers = workers.max(1);
+    let mut senders = Vec::with_capacity(workers);
+    let mut handles = Vec::with_capacity(workers);
+
+    for _ in 0..workers {
+        let (sender, receiver) = mpsc::sync_channel::<Transaction<B, N>>(SHARD_CHANNEL_BOUND);
+        senders.push(sender);
+        handles.push(thread::spawn(
+            move || -> Result<(AllBalances<B, N>, Vec<Rejection>)> {
+                let mut store = S::default();
+                let mut rejections = Vec::new();
+
+                for entry in receiver {
+                    let client = entry.client();
+                    let tx = entry.tx();
+                    if let Err(error) = apply_tx(&mut store, entry)? {
+                        rejections.push(Rejection { client, tx, error });
                     }
-                    // Previously intialized client
-                    Some(ref mut client) => client.apply_tx(entry),
                 }
+
+                Ok((store.into_balances(), rejections))
+            },
+        ));
+    }
+
+    let mut reader = init_reader(file)?;
+    for result in reader.deserialize::<Transaction<B, N>>() {
+        match result {
+            Ok(entry) => {
+                let shard = entry.client() as usize % workers;
+                // A send error means that shard's worker already returned
+                // (e.g. after a hard store failure); its error surfaces
+                // below once we join it, so there's nothing more to do here.
+                let _ = senders[shard].send(entry);
             }
             Err(e) => {
                 eprintln!("Failed to read CSV entry: {e}");
             }
         }
     }
+    drop(senders);
+
+    let mut balances = AllBalances::new();
+    let mut rejections = Vec::new();
+    for handle in handles {
+        let (shard_balances, shard_rejections) = handle
+            .join()
+            .map_err(|_| anyhow!("worker thread panicked"))??;
+        balances.extend(shard_balances);
+        rejections.extend(shard_rejections);
+    }
 
-    Ok(balances)
+    Ok((balances, rejections))
 }
 
-/// Run the program as configured by the arguments, and write the result to the
-/// standard output.
-pub fn load() -> Result<()> {
-    let file = env::args()
-        .nth(1)
-        .ok_or_else(|| anyhow!("no transactions filename passed"))?;
+/// Like [`process`], but reads the compact binary format (see [`binary`])
+/// instead of CSV, for inputs where CSV's serde overhead dominates runtime.
+/// The binary format only supports the default 64-bit, 4-digit currency
+/// (see [`model::Currency`]): it's a fixed wire format, not one that can
+/// grow or shrink its field widths per run the way the CSV/`--wide`/
+/// `--precision` path can.
+pub fn read_binary<S: Store<i64, 4>>(
+    file: &str,
+    mut store: S,
+) -> Result<(AllBalances<i64, 4>, Vec<Rejection>)> {
+    let mut reader = BufReader::new(File::open(file)?);
+    binary::read_header(&mut reader)?;
+
+    let mut rejections = Vec::new();
+    while let Some(entry) = binary::read_transaction(&mut reader)? {
+        let client = entry.client();
+        let tx = entry.tx();
+        if let Err(error) = apply_tx(&mut store, entry)? {
+            rejections.push(Rejection { client, tx, error });
+        }
+    }
+
+    Ok((store.into_balances(), rejections))
+}
+
+/// Writes `balances` using the compact binary format (see [`binary`]),
+/// mirroring the CSV [`csv::Writer`] loop in `run`.
+pub fn write_binary<W: Write>(mut writer: W, balances: &AllBalances<i64, 4>) -> Result<()> {
+    binary::write_header(&mut writer)?;
+    binary::write_count(&mut writer, balances.len() as u32)?;
+    for balance in balances.values() {
+        binary::write_balance(&mut writer, balance)?;
+    }
+
+    Ok(())
+}
+
+/// Runs the binary-format path (see [`read_binary`]/[`write_binary`]),
+/// which is only available for the default 64-bit, 4-digit currency; `load`
+/// has already checked that `cli` doesn't ask for `--wide`/a different
+/// `--precision` before calling this.
+fn run_binary(cli: &Cli) -> Result<()> {
+    let file = cli.input.as_deref().expect("--input required for .bin files");
+    let (clients, rejections) = if cli.disk_store {
+        read_binary(file, DiskStore::<i64, 4>::new()?)?
+    } else {
+        read_binary(file, MemStore::<i64, 4>::default())?
+    };
+    write_binary(io::stdout(), &clients)?;
+    print_rejections(&rejections);
+    Ok(())
+}
 
-    let clients = process(&file)?;
+/// Runs the CSV path (file or `--stdin`) for a currency backed by `B` with
+/// `N` digits of precision, falling back to the single-threaded [`process`]
+/// for small inputs (see [`process_parallel`]).
+fn run<B, const N: u32>(cli: Cli) -> Result<()>
+where
+    B: Backing,
+    MemStore<B, N>: Send + 'static,
+{
+    let workers = cli
+        .workers
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
 
-    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    let (clients, rejections) = match &cli.input {
+        Some(file) if cli.disk_store => process::<_, B, N>(file, DiskStore::<B, N>::new()?)?,
+        Some(file) => {
+            let file_len = std::fs::metadata(file).map(|m| m.len()).unwrap_or(0);
+            if workers > 1 && file_len >= PARALLEL_THRESHOLD_BYTES {
+                process_parallel::<MemStore<B, N>, B, N>(file, workers)?
+            } else {
+                process::<_, B, N>(file, MemStore::<B, N>::default())?
+            }
+        }
+        None if cli.disk_store => process_stdin::<_, B, N>(DiskStore::<B, N>::new()?)?,
+        None => process_stdin::<_, B, N>(MemStore::<B, N>::default())?,
+    };
+
+    let mut writer = csv::Writer::from_writer(io::stdout());
     for client in clients.values() {
         writer.serialize(client)?;
     }
 
+    print_rejections(&rejections);
     Ok(())
 }
+
+fn print_rejections(rejections: &[Rejection]) {
+    for rejection in rejections {
+        eprintln!(
+            "Rejected tx {} for client {}: {}",
+            rejection.tx, rejection.client, rejection.error
+        );
+    }
+}
+
+/// `--precision` picks a `const N: u32` at runtime, which has to be resolved
+/// to one of a finite set of monomorphizations of [`run`] at compile time.
+/// 0-9 comfortably covers every real currency (most use 2 or 4); anything
+/// past that is rejected rather than silently truncated.
+macro_rules! dispatch_precision {
+    ($backing:ty, $precision:expr, $cli:expr) => {
+        match $precision {
+            0 => run::<$backing, 0>($cli),
+            1 => run::<$backing, 1>($cli),
+            2 => run::<$backing, 2>($cli),
+            3 => run::<$backing, 3>($cli),
+            4 => run::<$backing, 4>($cli),
+            5 => run::<$backing, 5>($cli),
+            6 => run::<$backing, 6>($cli),
+            7 => run::<$backing, 7>($cli),
+            8 => run::<$backing, 8>($cli),
+            9 => run::<$backing, 9>($cli),
+            other => Err(anyhow!(
+                "unsupported --precision {other}: must be between 0 and 9"
+            )),
+        }
+    };
+}
+
+/// Run the program as configured by the CLI, and write the result to the
+/// standard output. `--precision`/`--wide` pick the currency's decimal
+/// precision and backing integer (see `crate::currency::Backing`); `--input`/
+/// `--stdin` pick where transactions come from. An `--input` file ending in
+/// `.bin` is read with the binary codec (see [`binary`]) instead of CSV,
+/// which only works with the default precision/backing.
+pub fn load() -> Result<()> {
+    let cli = Cli::parse();
+
+    let use_binary = cli.input.as_deref().is_some_and(|f| f.ends_with(".bin"));
+    if use_binary {
+        if cli.wide || cli.precision != 4 {
+            bail!(
+                "the binary format only supports the default 64-bit, 4-digit \
+                 currency; drop --wide/--precision, or use CSV instead"
+            );
+        }
+        return run_binary(&cli);
+    }
+
+    if cli.wide {
+        dispatch_precision!(i128, cli.precision, cli)
+    } else {
+        dispatch_precision!(i64, cli.precision, cli)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+
+    /// Sharding by client shouldn't change the result: running the same
+    /// multi-client input through [`process`] and [`process_parallel`] must
+    /// produce the same balances.
+    #[test]
+    fn test_parallel_matches_sequential() -> Result<()> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        writeln!(file, "type,client,tx,amount")?;
+        for client in 0..20_u16 {
+            writeln!(file, "deposit,{client},{},10.0", client as u32 * 10)?;
+            writeln!(file, "deposit,{client},{},5.0", client as u32 * 10 + 1)?;
+            writeln!(file, "withdrawal,{client},{},3.0", client as u32 * 10 + 2)?;
+        }
+        writeln!(file, "dispute,0,0,")?;
+        file.flush()?;
+
+        let path = file.path().to_str().expect("utf-8 path");
+        let (sequential, _) = process::<_, i64, 4>(path, MemStore::default())?;
+        let (parallel, _) = process_parallel::<MemStore<i64, 4>, i64, 4>(path, 4)?;
+
+        assert_eq!(sequential, parallel);
+
+        Ok(())
+    }
+
+    /// Runs `csv` (including its header row) through [`process`] against a
+    /// fresh [`MemStore`], for the rejection tests below.
+    fn run_csv(csv: &str) -> Result<Vec<Rejection>> {
+        let mut file = tempfile::NamedTempFile::new()?;
+        write!(file, "{csv}")?;
+        file.flush()?;
+
+        let path = file.path().to_str().expect("utf-8 path");
+        let (_, rejections) = process::<_, i64, 4>(path, MemStore::default())?;
+        Ok(rejections)
+    }
+
+    #[test]
+    fn test_rejects_duplicate_transaction() -> Result<()> {
+        let rejections = run_csv(
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,1,20.0\n",
+        )?;
+
+        assert_eq!(rejections.len(), 1);
+        assert!(matches!(rejections[0].error, LedgerError::DuplicateTransaction));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_insufficient_funds() -> Result<()> {
+        let rejections = run_csv(
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,20.0\n",
+        )?;
+
+        assert_eq!(rejections.len(), 1);
+        assert!(matches!(rejections[0].error, LedgerError::InsufficientFunds));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_operations_on_locked_account() -> Result<()> {
+        let rejections = run_csv(
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             dispute,1,1,\n\
+             chargeback,1,1,\n\
+             deposit,1,2,5.0\n",
+        )?;
+
+        assert_eq!(rejections.len(), 1);
+        assert!(matches!(rejections[0].error, LedgerError::AccountLocked));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_unknown_transaction() -> Result<()> {
+        let rejections = run_csv("type,client,tx,amount\ndispute,1,1,\n")?;
+
+        assert_eq!(rejections.len(), 1);
+        assert!(matches!(rejections[0].error, LedgerError::UnknownTransaction));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_already_disputed() -> Result<()> {
+        let rejections = run_csv(
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             dispute,1,1,\n\
+             dispute,1,1,\n",
+        )?;
+
+        assert_eq!(rejections.len(), 1);
+        assert!(matches!(rejections[0].error, LedgerError::AlreadyDisputed));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_not_disputed() -> Result<()> {
+        let rejections = run_csv(
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             resolve,1,1,\n",
+        )?;
+
+        assert_eq!(rejections.len(), 1);
+        assert!(matches!(rejections[0].error, LedgerError::NotDisputed));
+
+        Ok(())
+    }
+
+    /// A dispute referencing another client's transaction is rejected
+    /// outright, instead of being silently honored against the wrong
+    /// account (see `crate::store::Store::get_tx`).
+    #[test]
+    fn test_rejects_client_mismatch() -> Result<()> {
+        let rejections = run_csv(
+            "type,client,tx,amount\n\
+             deposit,1,1,1000.0\n\
+             dispute,2,1,\n",
+        )?;
+
+        assert_eq!(rejections.len(), 1);
+        assert!(matches!(rejections[0].error, LedgerError::ClientMismatch));
+
+        Ok(())
+    }
+}
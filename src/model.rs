@@ -1,18 +1,21 @@
-use crate::currency::PreciseCurrency;
+use crate::currency::{Backing, PreciseCurrency};
 
 use std::collections::HashMap;
+use std::fmt;
 
 use serde::{de, Deserialize, Serialize};
 
-/// We need 4 digits of precision by default
-pub type Currency = PreciseCurrency<4>;
+/// We need 4 digits of precision and an `i64` backing by default; `--wide`
+/// and `--precision` on the CLI pick a different [`Backing`]/`N` pair at
+/// runtime (see `crate::currency`).
+pub type Currency = PreciseCurrency<i64, 4>;
 
 /// I tried to optimize this by saving them into a fixed size array, but it was
 /// too large for the stack and it had to be boxed. Even in that case, it was
 /// slightly slower, so I ended up keeping the HashMap. Nevertheless, with more
 /// time this approach could still be viable, i.e., after reducing the size of
 /// the client, or with a number of clients large enough.
-pub type AllBalances = HashMap<u16, Balance>;
+pub type AllBalances<B, const N: u32> = HashMap<u16, Balance<B, N>>;
 
 /// The string fields are case insensitive. This is simpler than implementing
 /// `Deserialize` and is only needed once anyway.
@@ -33,14 +36,10 @@ where
     }
 }
 
-/// The transaction types supported for this implementation.
-///
-/// Another way to save the transactions would be with enum structs (i.e.
-/// `Deposit { client: u16, .. }`), since the amount is only necessary for
-/// deposits and withdrawals. However, the serialization was easier this way,
-/// and this only requires a couple controlled `unwrap`s.
+/// The raw transaction tag, only used to pick apart a [`TransactionRecord`]
+/// while it's converted into a proper [`Transaction`].
 #[derive(Debug, Deserialize, Eq, PartialEq)]
-pub enum TransactionType {
+enum TransactionType {
     Deposit,
     Withdrawal,
     Dispute,
@@ -48,60 +47,415 @@ pub enum TransactionType {
     Chargeback,
 }
 
-/// The input format, which is deserialized from the CSV thanks to serde. It's
-/// important to use `#[serde(default)]` when possible so that it's possible to
-/// be more flexible about the input fields by making them optional.
-#[derive(Debug, Deserialize, Eq, PartialEq)]
-pub struct Transaction {
+/// The input format as it comes out of the CSV, before it's known whether
+/// `amount` should or shouldn't be present. This only exists to be converted
+/// into a [`Transaction`] through `#[serde(try_from = "...")]`; nothing else
+/// should construct one directly.
+#[derive(Debug, Deserialize)]
+#[serde(bound = "")]
+struct TransactionRecord<B: Backing, const N: u32> {
     #[serde(
         rename = "type",
         deserialize_with = "case_insensitive_transaction_types"
     )]
-    pub _type: TransactionType,
+    _type: TransactionType,
     #[serde(default)]
-    pub client: u16,
+    client: u16,
     #[serde(default)]
-    pub tx: u32,
+    tx: u32,
     #[serde(default)]
-    pub amount: Option<Currency>,
+    amount: Option<PreciseCurrency<B, N>>,
+}
+
+/// Why a [`TransactionRecord`] couldn't be turned into a [`Transaction`].
+#[derive(Debug)]
+pub enum TransactionError {
+    /// A deposit or withdrawal didn't carry the `amount` it requires.
+    MissingAmount,
+    /// A dispute, resolve, or chargeback isn't supposed to carry an `amount`.
+    UnexpectedAmount,
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::MissingAmount => {
+                write!(f, "deposit/withdrawal is missing its amount")
+            }
+            TransactionError::UnexpectedAmount => {
+                write!(f, "dispute/resolve/chargeback must not carry an amount")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+/// The input format, deserialized from the CSV thanks to serde. Each variant
+/// structurally carries exactly the fields it needs, so `deposit`/
+/// `withdrawal`'s `amount` is never missing by the time the engine sees it,
+/// and a `dispute`/`resolve`/`chargeback` never has to pretend not to have
+/// one. Validation happens once, at parse time, in `TryFrom<TransactionRecord>`.
+/// Generic over the currency's [`Backing`] integer and its decimal precision
+/// `N`, both chosen by the caller (see the CLI's `--wide`/`--precision`).
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq)]
+#[serde(bound = "")]
+#[serde(try_from = "TransactionRecord<B, N>")]
+pub enum Transaction<B: Backing, const N: u32> {
+    Deposit {
+        client: u16,
+        tx: u32,
+        amount: PreciseCurrency<B, N>,
+    },
+    Withdrawal {
+        client: u16,
+        tx: u32,
+        amount: PreciseCurrency<B, N>,
+    },
+    Dispute {
+        client: u16,
+        tx: u32,
+    },
+    Resolve {
+        client: u16,
+        tx: u32,
+    },
+    Chargeback {
+        client: u16,
+        tx: u32,
+    },
 }
 
-/// Keeping track of what transactions have been disputed and in which ways.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum DisputeState {
-    /// Waiting for resolution
-    Waiting,
-    /// Already was resolved
+impl<B: Backing, const N: u32> Transaction<B, N> {
+    /// The client this transaction belongs to, regardless of variant.
+    pub fn client(&self) -> u16 {
+        match self {
+            Transaction::Deposit { client, .. }
+            | Transaction::Withdrawal { client, .. }
+            | Transaction::Dispute { client, .. }
+            | Transaction::Resolve { client, .. }
+            | Transaction::Chargeback { client, .. } => *client,
+        }
+    }
+
+    /// The transaction ID this transaction refers to, regardless of variant.
+    pub fn tx(&self) -> u32 {
+        match self {
+            Transaction::Deposit { tx, .. }
+            | Transaction::Withdrawal { tx, .. }
+            | Transaction::Dispute { tx, .. }
+            | Transaction::Resolve { tx, .. }
+            | Transaction::Chargeback { tx, .. } => *tx,
+        }
+    }
+}
+
+impl<B: Backing, const N: u32> TryFrom<TransactionRecord<B, N>> for Transaction<B, N> {
+    type Error = TransactionError;
+
+    fn try_from(record: TransactionRecord<B, N>) -> Result<Self, Self::Error> {
+        let TransactionRecord {
+            _type,
+            client,
+            tx,
+            amount,
+        } = record;
+
+        match _type {
+            TransactionType::Deposit => Ok(Transaction::Deposit {
+                client,
+                tx,
+                amount: amount.ok_or(TransactionError::MissingAmount)?,
+            }),
+            TransactionType::Withdrawal => Ok(Transaction::Withdrawal {
+                client,
+                tx,
+                amount: amount.ok_or(TransactionError::MissingAmount)?,
+            }),
+            TransactionType::Dispute => match amount {
+                None => Ok(Transaction::Dispute { client, tx }),
+                Some(_) => Err(TransactionError::UnexpectedAmount),
+            },
+            TransactionType::Resolve => match amount {
+                None => Ok(Transaction::Resolve { client, tx }),
+                Some(_) => Err(TransactionError::UnexpectedAmount),
+            },
+            TransactionType::Chargeback => match amount {
+                None => Ok(Transaction::Chargeback { client, tx }),
+                Some(_) => Err(TransactionError::UnexpectedAmount),
+            },
+        }
+    }
+}
+
+/// The funds a disputed transaction would move, if any. Withdrawals don't
+/// hold funds while disputed, so disputing or resolving one is a pure
+/// bookkeeping no-op, but a chargeback still needs to know which direction to
+/// reverse.
+#[derive(Copy, Clone, Debug)]
+pub enum DisputedAmount<B: Backing, const N: u32> {
+    Deposit(PreciseCurrency<B, N>),
+    Withdrawal(PreciseCurrency<B, N>),
+    None,
+}
+
+impl<B: Backing, const N: u32> From<&Transaction<B, N>> for DisputedAmount<B, N> {
+    fn from(tx: &Transaction<B, N>) -> Self {
+        match tx {
+            Transaction::Deposit { amount, .. } => DisputedAmount::Deposit(*amount),
+            Transaction::Withdrawal { amount, .. } => DisputedAmount::Withdrawal(*amount),
+            Transaction::Dispute { .. }
+            | Transaction::Resolve { .. }
+            | Transaction::Chargeback { .. } => DisputedAmount::None,
+        }
+    }
+}
+
+/// The dispute lifecycle of a single transaction. Every recorded transaction
+/// starts out `Processed`; from there only `Processed -> Disputed`,
+/// `Disputed -> Resolved`, `Disputed -> ChargedBack`, and `Resolved ->
+/// Disputed` (a previously resolved transaction can be disputed again) are
+/// legal. Each transition method below enforces this and, if allowed, moves
+/// the account's `available`/`held`/`total` accordingly. `TxState` itself
+/// doesn't carry a currency, so it stays generic only over the `Balance`/
+/// `DisputedAmount` its methods take.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TxState {
+    /// Applied normally; no dispute in progress.
+    #[default]
+    Processed,
+    /// Funds are held pending resolution.
+    Disputed,
+    /// A dispute was resolved in the client's favor; held funds were
+    /// released back to `available`.
     Resolved,
+    /// A dispute ended in a chargeback; the account is now locked.
+    ChargedBack,
 }
 
-/// The output format, which is also written to CSV thanks to serde.
-#[derive(Debug, Default, Deserialize, Serialize)]
+impl TxState {
+    /// `Processed -> Disputed` or `Resolved -> Disputed`: holds `amount` if
+    /// the disputed transaction was a deposit. Returns `None`, leaving the
+    /// account untouched, if `self` can't be disputed right now.
+    pub fn dispute<B: Backing, const N: u32>(
+        self,
+        account: &mut Balance<B, N>,
+        amount: DisputedAmount<B, N>,
+    ) -> Option<Self> {
+        match self {
+            TxState::Processed | TxState::Resolved => {
+                if let DisputedAmount::Deposit(amount) = amount {
+                    account.available -= amount;
+                    account.held += amount;
+                }
+                Some(TxState::Disputed)
+            }
+            _ => None,
+        }
+    }
+
+    /// `Disputed -> Resolved`: releases held funds back to `available`.
+    /// Returns `None`, leaving the account untouched, if `self` isn't
+    /// currently disputed.
+    pub fn resolve<B: Backing, const N: u32>(
+        self,
+        account: &mut Balance<B, N>,
+        amount: DisputedAmount<B, N>,
+    ) -> Option<Self> {
+        match self {
+            TxState::Disputed => {
+                if let DisputedAmount::Deposit(amount) = amount {
+                    account.available += amount;
+                    account.held -= amount;
+                }
+                Some(TxState::Resolved)
+            }
+            _ => None,
+        }
+    }
+
+    /// `Disputed -> ChargedBack`: reverses the disputed transaction and
+    /// freezes the account. Returns `None`, leaving the account untouched,
+    /// if `self` isn't currently disputed.
+    pub fn chargeback<B: Backing, const N: u32>(
+        self,
+        account: &mut Balance<B, N>,
+        amount: DisputedAmount<B, N>,
+    ) -> Option<Self> {
+        match self {
+            TxState::Disputed => {
+                match amount {
+                    DisputedAmount::Deposit(amount) => {
+                        account.held -= amount;
+                        account.total -= amount;
+                    }
+                    DisputedAmount::Withdrawal(amount) => {
+                        account.available += amount;
+                        account.total += amount;
+                    }
+                    DisputedAmount::None => {}
+                }
+                account.locked = true;
+                Some(TxState::ChargedBack)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The output format, which is also written to CSV thanks to serde. The full
+/// transaction log and dispute states used to live here too, but they're now
+/// owned by whichever [`crate::store::Store`] is backing the engine, since
+/// that's what lets the transaction log be kept off the heap for large
+/// inputs.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
-#[serde(default)]
-pub struct Balance {
+#[serde(default, bound = "")]
+pub struct Balance<B: Backing, const N: u32> {
     pub client: u16,
-    pub available: Currency,
-    pub held: Currency,
-    pub total: Currency,
+    pub available: PreciseCurrency<B, N>,
+    pub held: PreciseCurrency<B, N>,
+    pub total: PreciseCurrency<B, N>,
     pub locked: bool,
-
-    /// Saving the transactions for a user to check later in case of a dispute.
-    #[serde(skip)]
-    pub transactions: HashMap<u32, Transaction>,
-    /// If the transaction isn't in the map, then it isn't disputed.
-    #[serde(skip)]
-    pub disputes: HashMap<u32, DisputeState>,
 }
 
-/// No need to compare the transactions
-impl PartialEq for Balance {
-    fn eq(&self, other: &Self) -> bool {
-        self.client.eq(&other.client)
-            && self.available.eq(&other.available)
-            && self.held.eq(&other.held)
-            && self.total.eq(&other.total)
-            && self.locked.eq(&other.locked)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account() -> Balance<i64, 4> {
+        Balance::default()
+    }
+
+    fn deposit(amount: i64) -> DisputedAmount<i64, 4> {
+        DisputedAmount::Deposit(amount.into())
+    }
+
+    fn withdrawal(amount: i64) -> DisputedAmount<i64, 4> {
+        DisputedAmount::Withdrawal(amount.into())
+    }
+
+    #[test]
+    fn test_dispute_holds_deposit() {
+        let mut account = account();
+        account.available = 1000.into();
+        account.total = 1000.into();
+
+        let state = TxState::Processed.dispute(&mut account, deposit(1000));
+
+        assert_eq!(state, Some(TxState::Disputed));
+        assert_eq!(account.available, 0.into());
+        assert_eq!(account.held, 1000.into());
+        assert_eq!(account.total, 1000.into());
+    }
+
+    #[test]
+    fn test_dispute_withdrawal_is_a_noop() {
+        let mut account = account();
+        account.available = 1000.into();
+        account.total = 1000.into();
+
+        let state = TxState::Processed.dispute(&mut account, withdrawal(500));
+
+        assert_eq!(state, Some(TxState::Disputed));
+        assert_eq!(account.available, 1000.into());
+        assert_eq!(account.held, 0.into());
+        assert_eq!(account.total, 1000.into());
+    }
+
+    #[test]
+    fn test_double_dispute_rejected() {
+        let mut account = account();
+        assert_eq!(TxState::Disputed.dispute(&mut account, deposit(1000)), None);
+    }
+
+    #[test]
+    fn test_resolve_releases_held_funds() {
+        let mut account = account();
+        account.held = 1000.into();
+        account.total = 1000.into();
+
+        let state = TxState::Disputed.resolve(&mut account, deposit(1000));
+
+        assert_eq!(state, Some(TxState::Resolved));
+        assert_eq!(account.available, 1000.into());
+        assert_eq!(account.held, 0.into());
+        assert_eq!(account.total, 1000.into());
+    }
+
+    #[test]
+    fn test_resolve_without_dispute_rejected() {
+        let mut account = account();
+        assert_eq!(
+            TxState::Processed.resolve(&mut account, deposit(1000)),
+            None
+        );
+        assert_eq!(TxState::Resolved.resolve(&mut account, deposit(1000)), None);
+    }
+
+    #[test]
+    fn test_resolve_then_redispute() {
+        let mut account = account();
+        account.available = 1000.into();
+        account.total = 1000.into();
+
+        let state = TxState::Processed.dispute(&mut account, deposit(1000));
+        assert_eq!(state, Some(TxState::Disputed));
+
+        let state = state.unwrap().resolve(&mut account, deposit(1000));
+        assert_eq!(state, Some(TxState::Resolved));
+        assert_eq!(account.available, 1000.into());
+        assert_eq!(account.held, 0.into());
+
+        // A resolved transaction can be disputed again.
+        let state = state.unwrap().dispute(&mut account, deposit(1000));
+        assert_eq!(state, Some(TxState::Disputed));
+        assert_eq!(account.available, 0.into());
+        assert_eq!(account.held, 1000.into());
+    }
+
+    #[test]
+    fn test_chargeback_reverses_deposit_and_locks() {
+        let mut account = account();
+        account.held = 1000.into();
+        account.total = 1000.into();
+
+        let state = TxState::Disputed.chargeback(&mut account, deposit(1000));
+
+        assert_eq!(state, Some(TxState::ChargedBack));
+        assert_eq!(account.held, 0.into());
+        assert_eq!(account.total, 0.into());
+        assert!(account.locked);
+    }
+
+    #[test]
+    fn test_chargeback_reverses_withdrawal_and_locks() {
+        let mut account = account();
+        account.available = 500.into();
+        account.total = 500.into();
+
+        let state = TxState::Disputed.chargeback(&mut account, withdrawal(500));
+
+        assert_eq!(state, Some(TxState::ChargedBack));
+        assert_eq!(account.available, 1000.into());
+        assert_eq!(account.total, 1000.into());
+        assert!(account.locked);
+    }
+
+    #[test]
+    fn test_chargeback_without_dispute_rejected() {
+        let mut account = account();
+        assert_eq!(
+            TxState::Processed.chargeback(&mut account, deposit(1000)),
+            None
+        );
+        assert_eq!(
+            TxState::Resolved.chargeback(&mut account, deposit(1000)),
+            None
+        );
+        assert_eq!(
+            TxState::ChargedBack.chargeback(&mut account, deposit(1000)),
+            None
+        );
     }
 }
-impl Eq for Balance {}
@@ -0,0 +1,342 @@
+//! Abstracts over where account balances and the transaction log actually
+//! live, so [`crate::process`] doesn't have to care whether it's running
+//! against a plain in-memory map or something that spills to disk for inputs
+//! too large to fit in RAM.
+
+use crate::currency::{Backing, PreciseCurrency};
+use crate::model::{AllBalances, Balance, Transaction, TxState};
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use serde::{Deserialize, Serialize};
+
+/// Everything the engine needs to read and mutate accounts, and to look up
+/// past transactions for dispute/resolve/chargeback handling, without caring
+/// where that state is actually kept. Generic over the currency's
+/// [`Backing`] integer and its decimal precision `N`, both chosen by the
+/// caller.
+pub trait Store<B: Backing, const N: u32> {
+    /// Returns the account for `client`, creating a fresh, zeroed one on
+    /// first use.
+    fn get_account(&mut self, client: u16) -> &mut Balance<B, N>;
+
+    /// Remembers `tx` under its own ID for later lookups. Returns `Ok(true)`
+    /// if it was newly recorded, `Ok(false)` if that ID was already taken
+    /// (the transaction is a duplicate and should be ignored).
+    fn record_tx(&mut self, tx: Transaction<B, N>) -> io::Result<bool>;
+
+    /// Looks up a previously recorded transaction by ID.
+    fn get_tx(&mut self, tx: u32) -> io::Result<Option<&Transaction<B, N>>>;
+
+    /// Records the dispute lifecycle state of a transaction, overwriting
+    /// any previous one.
+    fn set_tx_state(&mut self, tx: u32, state: TxState);
+
+    /// Looks up the dispute lifecycle state of a transaction. Defaults to
+    /// `TxState::Processed` for any transaction that's never been
+    /// disputed.
+    fn get_tx_state(&mut self, tx: u32) -> TxState;
+
+    /// Consumes the store, returning every account it holds.
+    fn into_balances(self) -> AllBalances<B, N>;
+}
+
+/// Keeps every account, the full transaction log, and the dispute states
+/// resident in memory. This is today's behavior: simple, and fast as long as
+/// the input comfortably fits in RAM.
+#[derive(Default)]
+pub struct MemStore<B: Backing, const N: u32> {
+    balances: AllBalances<B, N>,
+    transactions: HashMap<u32, Transaction<B, N>>,
+    states: HashMap<u32, TxState>,
+}
+
+impl<B: Backing, const N: u32> Store<B, N> for MemStore<B, N> {
+    fn get_account(&mut self, client: u16) -> &mut Balance<B, N> {
+        self.balances.entry(client).or_insert_with(|| Balance {
+            client,
+            ..Balance::default()
+        })
+    }
+
+    fn record_tx(&mut self, tx: Transaction<B, N>) -> io::Result<bool> {
+        match self.transactions.entry(tx.tx()) {
+            Entry::Occupied(_) => Ok(false),
+            Entry::Vacant(entry) => {
+                entry.insert(tx);
+                Ok(true)
+            }
+        }
+    }
+
+    fn get_tx(&mut self, tx: u32) -> io::Result<Option<&Transaction<B, N>>> {
+        Ok(self.transactions.get(&tx))
+    }
+
+    fn set_tx_state(&mut self, tx: u32, state: TxState) {
+        self.states.insert(tx, state);
+    }
+
+    fn get_tx_state(&mut self, tx: u32) -> TxState {
+        self.states.get(&tx).copied().unwrap_or_default()
+    }
+
+    fn into_balances(self) -> AllBalances<B, N> {
+        self.balances
+    }
+}
+
+/// [`Transaction`]'s own `Serialize`/`Deserialize` is tuned for the CSV/JSON
+/// wire format, where a [`PreciseCurrency`] amount is a decimal string that
+/// only `deserialize_f64` knows how to read back; round-tripping one through
+/// plain `serde_json` (as [`DiskStore`]'s log does) would fail on that
+/// string/number mismatch. This mirrors a transaction's shape but keeps the
+/// amount as its raw fixed-point integer, so it round-trips losslessly.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+struct DiskRecord<B: Backing> {
+    tag: DiskTag,
+    client: u16,
+    tx: u32,
+    amount: Option<B>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum DiskTag {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+}
+
+fn to_disk_record<B: Backing, const N: u32>(tx: &Transaction<B, N>) -> DiskRecord<B> {
+    match *tx {
+        Transaction::Deposit { client, tx, amount } => DiskRecord {
+            tag: DiskTag::Deposit,
+            client,
+            tx,
+            amount: Some(amount.raw()),
+        },
+        Transaction::Withdrawal { client, tx, amount } => DiskRecord {
+            tag: DiskTag::Withdrawal,
+            client,
+            tx,
+            amount: Some(amount.raw()),
+        },
+        Transaction::Dispute { client, tx } => DiskRecord {
+            tag: DiskTag::Dispute,
+            client,
+            tx,
+            amount: None,
+        },
+        Transaction::Resolve { client, tx } => DiskRecord {
+            tag: DiskTag::Resolve,
+            client,
+            tx,
+            amount: None,
+        },
+        Transaction::Chargeback { client, tx } => DiskRecord {
+            tag: DiskTag::Chargeback,
+            client,
+            tx,
+            amount: None,
+        },
+    }
+}
+
+fn from_disk_record<B: Backing, const N: u32>(record: DiskRecord<B>) -> Transaction<B, N> {
+    let DiskRecord {
+        tag,
+        client,
+        tx,
+        amount,
+    } = record;
+    let amount = || PreciseCurrency::from_raw(amount.expect("deposit/withdrawal carries an amount"));
+
+    match tag {
+        DiskTag::Deposit => Transaction::Deposit {
+            client,
+            tx,
+            amount: amount(),
+        },
+        DiskTag::Withdrawal => Transaction::Withdrawal {
+            client,
+            tx,
+            amount: amount(),
+        },
+        DiskTag::Dispute => Transaction::Dispute { client, tx },
+        DiskTag::Resolve => Transaction::Resolve { client, tx },
+        DiskTag::Chargeback => Transaction::Chargeback { client, tx },
+    }
+}
+
+/// How many transactions [`DiskStore`] keeps resident in its cache at once.
+/// Bounds the cache to a fixed footprint regardless of input size: once it's
+/// full, the oldest entry is evicted to make room, so a dispute/resolve/
+/// chargeback on a cold transaction costs a disk read but never grows RAM.
+const DISK_CACHE_CAPACITY: usize = 1024;
+
+/// Spills the transaction log to a temporary file instead of keeping every
+/// transaction resident, while still keeping accounts and dispute states in
+/// memory: there are at most `u16::MAX` accounts, so those stay cheap, but
+/// the transaction log itself grows without bound on large inputs. Only a
+/// small in-memory index of byte offsets is kept for every transaction, plus
+/// a bounded cache (see [`DISK_CACHE_CAPACITY`]) of the most recently
+/// recorded or loaded ones, so recent transactions don't round-trip through
+/// disk on every lookup but memory still stays flat on inputs with many
+/// distinct transactions. A cache hit doesn't refresh an entry's place in
+/// the eviction order.
+pub struct DiskStore<B: Backing, const N: u32> {
+    balances: AllBalances<B, N>,
+    states: HashMap<u32, TxState>,
+    log: File,
+    index: HashMap<u32, u64>,
+    cache: HashMap<u32, Transaction<B, N>>,
+    cache_order: VecDeque<u32>,
+}
+
+impl<B: Backing, const N: u32> DiskStore<B, N> {
+    /// Creates a new disk-backed store, spilling its transaction log to a
+    /// fresh temporary file that's removed once the store is dropped.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            balances: AllBalances::new(),
+            states: HashMap::new(),
+            log: tempfile::tempfile()?,
+            index: HashMap::new(),
+            cache: HashMap::new(),
+            cache_order: VecDeque::new(),
+        })
+    }
+
+    /// Caches `tx` under `id`, evicting the oldest cached transaction(s) if
+    /// that would put the cache over [`DISK_CACHE_CAPACITY`].
+    fn cache_insert(&mut self, id: u32, tx: Transaction<B, N>) {
+        if self.cache.insert(id, tx).is_none() {
+            self.cache_order.push_back(id);
+        }
+        while self.cache.len() > DISK_CACHE_CAPACITY {
+            let Some(evicted) = self.cache_order.pop_front() else {
+                break;
+            };
+            self.cache.remove(&evicted);
+        }
+    }
+}
+
+impl<B: Backing, const N: u32> Store<B, N> for DiskStore<B, N> {
+    fn get_account(&mut self, client: u16) -> &mut Balance<B, N> {
+        self.balances.entry(client).or_insert_with(|| Balance {
+            client,
+            ..Balance::default()
+        })
+    }
+
+    fn record_tx(&mut self, tx: Transaction<B, N>) -> io::Result<bool> {
+        if self.index.contains_key(&tx.tx()) {
+            return Ok(false);
+        }
+
+        let id = tx.tx();
+        let bytes = serde_json::to_vec(&to_disk_record(&tx)).map_err(io::Error::other)?;
+
+        let offset = self.log.seek(SeekFrom::End(0))?;
+        self.log.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.log.write_all(&bytes)?;
+
+        self.index.insert(id, offset);
+        self.cache_insert(id, tx);
+        Ok(true)
+    }
+
+    fn get_tx(&mut self, tx: u32) -> io::Result<Option<&Transaction<B, N>>> {
+        if self.cache.contains_key(&tx) {
+            return Ok(self.cache.get(&tx));
+        }
+
+        let Some(&offset) = self.index.get(&tx) else {
+            return Ok(None);
+        };
+
+        self.log.seek(SeekFrom::Start(offset))?;
+        let mut len_buf = [0; 4];
+        self.log.read_exact(&mut len_buf)?;
+
+        let mut buf = vec![0; u32::from_le_bytes(len_buf) as usize];
+        self.log.read_exact(&mut buf)?;
+        let record: DiskRecord<B> = serde_json::from_slice(&buf).map_err(io::Error::other)?;
+        let parsed = from_disk_record(record);
+
+        self.cache_insert(tx, parsed);
+        Ok(self.cache.get(&tx))
+    }
+
+    fn set_tx_state(&mut self, tx: u32, state: TxState) {
+        self.states.insert(tx, state);
+    }
+
+    fn get_tx_state(&mut self, tx: u32) -> TxState {
+        self.states.get(&tx).copied().unwrap_or_default()
+    }
+
+    fn into_balances(self) -> AllBalances<B, N> {
+        self.balances
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+
+    fn deposit(tx: u32, client: u16, amount: i64) -> Transaction<i64, 4> {
+        Transaction::Deposit {
+            client,
+            tx,
+            amount: amount.into(),
+        }
+    }
+
+    /// A transaction must read back the same after it's spilled to disk and
+    /// evicted from the cache, not just while it's still cache-resident.
+    #[test]
+    fn test_disk_round_trip() -> Result<()> {
+        let mut store = DiskStore::<i64, 4>::new()?;
+        store.record_tx(deposit(1, 1, 12345))?;
+
+        // Force the entry out of the cache so `get_tx` has to hit the log.
+        for i in 0..DISK_CACHE_CAPACITY as u32 {
+            store.record_tx(deposit(i + 100, 2, 1))?;
+        }
+
+        assert_eq!(store.get_tx(1)?, Some(&deposit(1, 1, 12345)));
+        Ok(())
+    }
+
+    /// A duplicate transaction ID is rejected exactly like [`MemStore`]'s.
+    #[test]
+    fn test_disk_duplicate() -> Result<()> {
+        let mut store = DiskStore::<i64, 4>::new()?;
+        assert!(store.record_tx(deposit(1, 1, 100))?);
+        assert!(!store.record_tx(deposit(1, 1, 999))?);
+        Ok(())
+    }
+
+    /// The cache never grows past [`DISK_CACHE_CAPACITY`], regardless of how
+    /// many distinct transactions pass through the store.
+    #[test]
+    fn test_disk_cache_bounded() -> Result<()> {
+        let mut store = DiskStore::<i64, 4>::new()?;
+        for i in 0..(DISK_CACHE_CAPACITY as u32 * 4) {
+            store.record_tx(deposit(i, 1, 1))?;
+        }
+        assert!(store.cache.len() <= DISK_CACHE_CAPACITY);
+        assert_eq!(store.index.len(), DISK_CACHE_CAPACITY * 4);
+        Ok(())
+    }
+}
@@ -1,27 +1,36 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
 
 use transaction_system::model::Balance;
+use transaction_system::store::MemStore;
 
 fn run_test(input: &str, output: &str) -> Result<()> {
     let test_dir = env!("CARGO_MANIFEST_DIR");
     let input = format!("{test_dir}/tests/{input}");
     let output = format!("{test_dir}/tests/{output}");
 
-    let ret_balances = transaction_system::process(&input)?;
+    let (ret_balances, _rejections) =
+        transaction_system::process::<_, i64, 4>(&input, MemStore::default())?;
 
-    let mut expected_balances = Balance::init_all();
+    let mut expected_balances: HashMap<u16, Balance<i64, 4>> = HashMap::new();
     let mut reader = transaction_system::init_reader(&output)?;
-    for result in reader.deserialize::<Balance>() {
+    for result in reader.deserialize::<Balance<i64, 4>>() {
         let entry = result?;
-        let client = entry.client as usize;
-        expected_balances[client] = Some(entry);
+        expected_balances.insert(entry.client, entry);
     }
 
-    // Prettier on console by iterating one by one
-    let mut i = 1;
-    for (ret, expected) in ret_balances.into_iter().zip(expected_balances) {
-        assert_eq!(ret, expected, "ret != {output} for client {i}");
-        i += 1;
+    assert_eq!(
+        ret_balances.len(),
+        expected_balances.len(),
+        "client count != {output}"
+    );
+    for (client, expected) in &expected_balances {
+        assert_eq!(
+            ret_balances.get(client),
+            Some(expected),
+            "ret != {output} for client {client}"
+        );
     }
 
     Ok(())